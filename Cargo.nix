@@ -171,6 +171,7 @@ rec {
           "Till Höppner <till@hoeppner.ws>"
         ];
         dependencies = {
+          "atty" = "atty 0.2.13 (registry+https://github.com/rust-lang/crates.io-index)";
           "clap" = "clap 2.33.0 (registry+https://github.com/rust-lang/crates.io-index)";
           "vec_map" = "vec_map 0.8.1 (registry+https://github.com/rust-lang/crates.io-index)";
         };
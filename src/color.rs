@@ -0,0 +1,307 @@
+use std::str::FromStr;
+
+use cpuline::CPU;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+    Blue,
+    Magenta
+}
+
+impl Color {
+    fn name(self) -> &'static str {
+        match self {
+            Color::Green => "green",
+            Color::Yellow => "yellow",
+            Color::Red => "red",
+            Color::Blue => "blue",
+            Color::Magenta => "magenta"
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Color::Green => "\x1b[32m",
+            Color::Yellow => "\x1b[33m",
+            Color::Red => "\x1b[31m",
+            Color::Blue => "\x1b[34m",
+            Color::Magenta => "\x1b[35m"
+        }
+    }
+}
+
+/// How colors are written into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Terminal escape sequences
+    Ansi,
+    /// `#[fg=...]` for the tmux status line
+    Tmux,
+    /// `<span foreground="...">` for bars like waybar or i3blocks
+    Pango
+}
+
+impl Markup {
+    pub const NAMES: &'static [&'static str] = &["ansi", "tmux", "pango"];
+}
+
+impl FromStr for Markup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Markup, String> {
+        match s {
+            "ansi" => Ok(Markup::Ansi),
+            "tmux" => Ok(Markup::Tmux),
+            "pango" => Ok(Markup::Pango),
+            _ => Err(format!("unknown markup {:?}", s))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    Always,
+    Never,
+    Auto
+}
+
+impl When {
+    pub const NAMES: &'static [&'static str] = &["always", "never", "auto"];
+
+    /// Whether to color at all. Escape sequences only make sense on a
+    /// terminal, but tmux and pango markup is always meant for a program.
+    pub fn enabled(self, markup: Markup) -> bool {
+        match self {
+            When::Always => true,
+            When::Never => false,
+            When::Auto => markup != Markup::Ansi || atty::is(atty::Stream::Stdout)
+        }
+    }
+}
+
+impl FromStr for When {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<When, String> {
+        match s {
+            "always" => Ok(When::Always),
+            "never" => Ok(When::Never),
+            "auto" => Ok(When::Auto),
+            _ => Err(format!("unknown color mode {:?}", s))
+        }
+    }
+}
+
+/// What the color of a glyph tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBy {
+    /// How busy the core is, green to yellow to red
+    Load,
+    /// Where most of the busy time went: user green, system blue,
+    /// iowait magenta and steal red
+    State
+}
+
+impl ColorBy {
+    pub const NAMES: &'static [&'static str] = &["load", "state"];
+}
+
+impl FromStr for ColorBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorBy, String> {
+        match s {
+            "load" => Ok(ColorBy::Load),
+            "state" => Ok(ColorBy::State),
+            _ => Err(format!("unknown color scheme {:?}", s))
+        }
+    }
+}
+
+/// Picks colors for cores and the markup to write them in.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub markup: Markup,
+    pub by: ColorBy,
+    /// Percentages at which the load turns yellow and red
+    pub yellow: f64,
+    pub red: f64
+}
+
+impl Palette {
+    pub fn for_core(&self, core: &CPU) -> Option<Color> {
+        match self.by {
+            ColorBy::Load => Some(self.for_percent(match core.total_time() {
+                0 => 0.,
+                total => 100. * core.busy_time() as f64 / total as f64
+            })),
+            ColorBy::State => {
+                // iowait counts as idle time, but a core waiting on IO is
+                // the interesting case, so it competes with the busy states
+                let states = [
+                    (core.user_time(), Color::Green),
+                    (core.system_time(), Color::Blue),
+                    (core.iowait(), Color::Magenta),
+                    (core.steal(), Color::Red)
+                ];
+                states.iter()
+                    .filter(|(time, _)| *time > 0)
+                    .max_by_key(|(time, _)| *time)
+                    .map(|(_, color)| *color)
+            }
+        }
+    }
+
+    pub fn for_percent(&self, percent: f64) -> Color {
+        if percent >= self.red {
+            Color::Red
+        } else if percent >= self.yellow {
+            Color::Yellow
+        } else {
+            Color::Green
+        }
+    }
+}
+
+/// Builds a line of text, only switching colors where they change.
+pub struct Canvas {
+    markup: Option<Markup>,
+    out: String,
+    current: Option<Color>
+}
+
+impl Canvas {
+    /// A canvas that ignores all colors if `markup` is `None`.
+    pub fn new(markup: Option<Markup>) -> Canvas {
+        Canvas { markup, out: String::new(), current: None }
+    }
+
+    pub fn push(&mut self, color: Option<Color>, c: char) {
+        let mut buf = [0; 4];
+        self.push_str(color, c.encode_utf8(&mut buf));
+    }
+
+    pub fn push_str(&mut self, color: Option<Color>, s: &str) {
+        let markup = match self.markup {
+            Some(markup) => markup,
+            None => return self.out.push_str(s)
+        };
+
+        if color != self.current {
+            self.close();
+            if let Some(color) = color {
+                match markup {
+                    Markup::Ansi => self.out.push_str(color.ansi()),
+                    Markup::Tmux => { self.out.push_str("#[fg="); self.out.push_str(color.name()); self.out.push(']') },
+                    Markup::Pango => { self.out.push_str("<span foreground=\""); self.out.push_str(color.name()); self.out.push_str("\">") }
+                }
+            }
+            self.current = color;
+        }
+
+        for c in s.chars() {
+            match (markup, c) {
+                (Markup::Pango, '&') => self.out.push_str("&amp;"),
+                (Markup::Pango, '<') => self.out.push_str("&lt;"),
+                (Markup::Pango, '>') => self.out.push_str("&gt;"),
+                // tmux turns ## into a single #
+                (Markup::Tmux, '#') => self.out.push_str("##"),
+                (_, c) => self.out.push(c)
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if self.current.take().is_some() {
+            match self.markup {
+                Some(Markup::Ansi) => self.out.push_str("\x1b[0m"),
+                Some(Markup::Tmux) => self.out.push_str("#[default]"),
+                Some(Markup::Pango) => self.out.push_str("</span>"),
+                None => ()
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> String {
+        self.close();
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_escapes_hashes() {
+        let mut canvas = Canvas::new(Some(Markup::Tmux));
+        canvas.push(Some(Color::Red), '#');
+        canvas.push(Some(Color::Red), '#');
+        canvas.push_str(None, " #[");
+        // Two glyphs, then the reset
+        assert_eq!(canvas.finish(), "#[fg=red]#####[default] ##[");
+    }
+
+    #[test]
+    fn switches_color_only_on_change() {
+        let mut canvas = Canvas::new(Some(Markup::Ansi));
+        canvas.push(Some(Color::Red), 'a');
+        canvas.push(Some(Color::Red), 'b');
+        canvas.push(Some(Color::Green), 'c');
+        canvas.push(None, ' ');
+        canvas.push(Some(Color::Green), 'd');
+        assert_eq!(canvas.finish(), "\x1b[31mab\x1b[0m\x1b[32mc\x1b[0m \x1b[32md\x1b[0m");
+    }
+
+    #[test]
+    fn resets_on_finish() {
+        let finish = |markup| {
+            let mut canvas = Canvas::new(markup);
+            canvas.push(Some(Color::Blue), 'x');
+            canvas.finish()
+        };
+        assert_eq!(finish(None), "x");
+        assert_eq!(finish(Some(Markup::Ansi)), "\x1b[34mx\x1b[0m");
+        assert_eq!(finish(Some(Markup::Tmux)), "#[fg=blue]x#[default]");
+        assert_eq!(finish(Some(Markup::Pango)), "<span foreground=\"blue\">x</span>");
+    }
+
+    #[test]
+    fn pango_escapes_markup() {
+        let mut canvas = Canvas::new(Some(Markup::Pango));
+        canvas.push_str(None, "<&>");
+        assert_eq!(canvas.finish(), "&lt;&amp;&gt;");
+    }
+
+    #[test]
+    fn load_colors_change_at_thresholds() {
+        let palette = Palette { markup: Markup::Ansi, by: ColorBy::Load, yellow: 50., red: 80. };
+        assert_eq!(palette.for_percent(0.), Color::Green);
+        assert_eq!(palette.for_percent(49.9), Color::Green);
+        assert_eq!(palette.for_percent(50.), Color::Yellow);
+        assert_eq!(palette.for_percent(79.9), Color::Yellow);
+        assert_eq!(palette.for_percent(80.), Color::Red);
+        assert_eq!(palette.for_percent(100.), Color::Red);
+    }
+
+    #[test]
+    fn load_colors_follow_busy_time() {
+        let palette = Palette { markup: Markup::Ansi, by: ColorBy::Load, yellow: 50., red: 80. };
+        let color = |line| palette.for_core(&CPU::from_line(line).unwrap());
+        assert_eq!(color("10 0 20 70"), Some(Color::Green));
+        assert_eq!(color("10 40 30 20"), Some(Color::Red));
+        assert_eq!(color("0 0 0 0"), Some(Color::Green));
+    }
+
+    #[test]
+    fn state_colors_follow_the_largest_state() {
+        let palette = Palette { markup: Markup::Ansi, by: ColorBy::State, yellow: 50., red: 80. };
+        let color = |line| palette.for_core(&CPU::from_line(line).unwrap());
+        assert_eq!(color("10 0 20 70"), Some(Color::Blue));
+        assert_eq!(color("10 0 0 50 30"), Some(Color::Magenta));
+        assert_eq!(color("0 0 0 100"), None);
+    }
+}
@@ -1,12 +1,14 @@
-extern crate vec_map;
-
 use std::{
     fs::File,
-    io::{ self, BufRead, BufReader }
+    io::{ BufRead, BufReader },
+    path::Path
 };
 use vec_map::VecMap;
 
-const PROC_STAT: &'static str = "/proc/stat";
+use crate::error::{Error, ParseError};
+
+/// Where procfs is usually mounted
+pub const PROC_ROOT: &str = "/proc";
 
 /// cpu  3357 0 4313 1362393
 ///   The  amount  of  time, measured in units of USER_HZ (1/100ths of a
@@ -51,46 +53,92 @@ pub struct Stat {
 }
 
 impl Stat {
-    pub fn read() -> io::Result<Stat> {
-        let file = File::open(PROC_STAT)?;
-        let reader = BufReader::new(file);
+    /// Reads `/proc/stat`.
+    pub fn read() -> Result<Stat, Error> {
+        Stat::read_in(PROC_ROOT)
+    }
+
+    /// Reads `stat` from procfs mounted at `proc_root`, e.g. a host's
+    /// `/proc` bind-mounted into a container at `/host/proc`.
+    pub fn read_in<P: AsRef<Path>>(proc_root: P) -> Result<Stat, Error> {
+        let file = File::open(proc_root.as_ref().join("stat"))?;
+        Stat::read_from(BufReader::new(file))
+    }
+
+    /// Parses `/proc/stat` formatted text from any reader.
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Stat, Error> {
         let mut stat = Stat { total: None, cores: VecMap::new() };
 
         for line in reader.lines() {
             let line = line?;
             const OFFSET: usize = 3; // "cpu".len()
             if line.starts_with("cpu ") {
-                stat.total = Some(CPU::from_line(&line[OFFSET..])); 
+                let cpu = CPU::from_line(&line[OFFSET..]).map_err(|e| e.with_line(&line))?;
+                stat.total = Some(cpu);
             } else if line.starts_with("cpu") {
-                let first_space = line.find(' ').unwrap();
-                let num: u64 = line[OFFSET..first_space].parse().unwrap();
-                let cpu = CPU::from_line(&line[first_space..]);
-                stat.cores.insert(num as usize, cpu);
+                let bad_index = || ParseError::BadCpuIndex { line: line.clone() };
+                let first_space = line.find(' ').ok_or_else(bad_index)?;
+                let num: usize = line[OFFSET..first_space].parse().map_err(|_| bad_index())?;
+                let cpu = CPU::from_line(&line[first_space..]).map_err(|e| e.with_line(&line))?;
+                stat.cores.insert(num, cpu);
             }
         }
 
         Ok(stat)
     }
 
-    pub fn load_since(&self, earlier: &Stat) -> Load {
+    /// The aggregate of all cores, from the `cpu ` line.
+    pub fn total(&self) -> Option<&CPU> { self.total.as_ref() }
+    /// Individual cores, keyed by their number.
+    pub fn cores(&self) -> &VecMap<CPU> { &self.cores }
+
+    /// The load since boot, as if `self` were compared to a reading with
+    /// all counters at zero.
+    pub fn since_boot(&self) -> Load {
         Load {
+            total: self.total.clone(),
+            cores: self.cores.clone(),
+            invalid: Vec::new()
+        }
+    }
+
+    /// The load between `earlier` and `self`.
+    ///
+    /// Cores missing from either reading are left out; cores whose counters
+    /// went backwards are left out and listed in [`Load::invalid`].
+    pub fn load_since(&self, earlier: &Stat) -> Load {
+        let mut load = Load {
             total: match (&self.total, &earlier.total) {
-                (&Some(ref now), &Some(ref old)) => Some(now.diff(old)),
+                (Some(now), Some(old)) => now.checked_diff(old),
                 _ => None
             },
-            cores: self.cores.iter()
-                .flat_map(|(idx, core)| earlier.cores.get(idx).map(|ec| (idx, core.diff(ec))))
-                .collect()
+            ..Load::default()
+        };
+
+        for (idx, core) in self.cores.iter() {
+            if let Some(old) = earlier.cores.get(idx) {
+                match core.checked_diff(old) {
+                    Some(diff) => { load.cores.insert(idx, diff); },
+                    None => load.invalid.push(idx)
+                }
+            }
         }
+
+        load
     }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Load {
+    /// `None` if either reading lacked a total, or its counters went backwards
     pub total: Option<CPU>,
-    pub cores: VecMap<CPU>
+    pub cores: VecMap<CPU>,
+    /// Cores whose counters went backwards between the readings (hotplug,
+    /// VM migration, counter wraparound), so there is no usable sample
+    pub invalid: Vec<usize>
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone)]
 pub struct CPU {
     user: u64,
@@ -102,42 +150,138 @@ pub struct CPU {
     softirq: u64,
     steal: u64,
     guest: u64,
-    guest_nice: u64
+    guest_nice: u64,
+    extra: Vec<u64>
 }
 
 impl CPU {
-    pub fn from_line(line: &str) -> CPU {
-        fn parse(s: Option<&str>) -> u64 { s.and_then(|s| s.trim().parse().ok()).expect("Couldn't parse CPU stat") }
+    /// Parses the numbers following a `cpu` or `cpuN` label.
+    ///
+    /// Only the first four fields exist on every kernel, later ones default
+    /// to zero when missing. Columns beyond `guest_nice` are kept in
+    /// [`extra`](CPU::extra) for kernels newer than this parser.
+    pub fn from_line(line: &str) -> Result<CPU, ParseError> {
         let mut tok = line.split_whitespace();
-        let user = parse(tok.next());
-        let nice = parse(tok.next());
-        let system = parse(tok.next());
-        let idle = parse(tok.next());
-        let iowait = parse(tok.next());
-        let irq = parse(tok.next());
-        let softirq = parse(tok.next());
-        let steal = parse(tok.next());
-        let guest = parse(tok.next());
-        let guest_nice = parse(tok.next());
-
-        CPU { user, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice }
+        let number = |field: &'static str, s: &str| -> Result<u64, ParseError> {
+            s.parse().map_err(|_| ParseError::BadNumber { field, line: line.to_owned() })
+        };
+        let mut required = |field: &'static str| -> Result<u64, ParseError> {
+            let s = tok.next().ok_or_else(|| ParseError::MissingField { field, line: line.to_owned() })?;
+            number(field, s)
+        };
+        let user = required("user")?;
+        let nice = required("nice")?;
+        let system = required("system")?;
+        let idle = required("idle")?;
+
+        let mut optional = |field: &'static str| -> Result<u64, ParseError> {
+            tok.next().map_or(Ok(0), |s| number(field, s))
+        };
+        let iowait = optional("iowait")?;
+        let irq = optional("irq")?;
+        let softirq = optional("softirq")?;
+        let steal = optional("steal")?;
+        let guest = optional("guest")?;
+        let guest_nice = optional("guest_nice")?;
+
+        let extra = tok.map(|s| number("extra", s)).collect::<Result<_, _>>()?;
+
+        Ok(CPU { user, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice, extra })
+    }
+
+    /// All counters in `/proc/stat` column order, including extra ones.
+    pub(crate) fn fields(&self) -> Vec<u64> {
+        let mut fields = vec![self.user, self.nice, self.system, self.idle, self.iowait,
+                              self.irq, self.softirq, self.steal, self.guest, self.guest_nice];
+        fields.extend_from_slice(&self.extra);
+        fields
+    }
+
+    /// The inverse of [`fields`](CPU::fields), missing counters being zero.
+    pub(crate) fn from_fields(fields: &[u64]) -> CPU {
+        let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+        CPU {
+            user: field(0),
+            nice: field(1),
+            system: field(2),
+            idle: field(3),
+            iowait: field(4),
+            irq: field(5),
+            softirq: field(6),
+            steal: field(7),
+            guest: field(8),
+            guest_nice: field(9),
+            extra: fields.get(10..).unwrap_or(&[]).to_vec()
+        }
+    }
+
+    /// The time of both `self` and `other` in each state.
+    pub fn add(&self, other: &CPU) -> CPU {
+        let (mut fields, other) = (self.fields(), other.fields());
+        if fields.len() < other.len() {
+            fields.resize(other.len(), 0);
+        }
+        for (field, other) in fields.iter_mut().zip(other) {
+            *field += other;
+        }
+        CPU::from_fields(&fields)
+    }
+
+    /// The time spent in each state since `other`, or `None` if any
+    /// counter is smaller than in `other`.
+    pub fn checked_diff(&self, other: &CPU) -> Option<CPU> {
+        if self.extra.len() != other.extra.len() {
+            return None;
+        }
+
+        Some(CPU {
+            user: self.user.checked_sub(other.user)?,
+            nice: self.nice.checked_sub(other.nice)?,
+            system: self.system.checked_sub(other.system)?,
+            idle: self.idle.checked_sub(other.idle)?,
+            iowait: self.iowait.checked_sub(other.iowait)?,
+            irq: self.irq.checked_sub(other.irq)?,
+            softirq: self.softirq.checked_sub(other.softirq)?,
+            steal: self.steal.checked_sub(other.steal)?,
+            guest: self.guest.checked_sub(other.guest)?,
+            guest_nice: self.guest_nice.checked_sub(other.guest_nice)?,
+            extra: self.extra.iter().zip(&other.extra)
+                .map(|(a, b)| a.checked_sub(*b))
+                .collect::<Option<_>>()?
+        })
     }
 
+    /// Like [`checked_diff`](CPU::checked_diff), but counters that went
+    /// backwards count as zero.
     pub fn diff(&self, other: &CPU) -> CPU {
         CPU {
-            user: self.user - other.user,
-            nice: self.nice - other.nice,
-            system: self.system - other.system,
-            idle: self.idle - other.idle,
-            iowait: self.iowait - other.iowait,
-            irq: self.irq - other.irq,
-            softirq: self.softirq - other.softirq,
-            steal: self.steal - other.steal,
-            guest: self.guest - other.guest,
-            guest_nice: self.guest_nice - other.guest_nice
+            user: self.user.saturating_sub(other.user),
+            nice: self.nice.saturating_sub(other.nice),
+            system: self.system.saturating_sub(other.system),
+            idle: self.idle.saturating_sub(other.idle),
+            iowait: self.iowait.saturating_sub(other.iowait),
+            irq: self.irq.saturating_sub(other.irq),
+            softirq: self.softirq.saturating_sub(other.softirq),
+            steal: self.steal.saturating_sub(other.steal),
+            guest: self.guest.saturating_sub(other.guest),
+            guest_nice: self.guest_nice.saturating_sub(other.guest_nice),
+            extra: self.extra.iter().zip(&other.extra).map(|(a, b)| a.saturating_sub(*b)).collect()
         }
     }
 
+    pub fn user(&self) -> u64 { self.user }
+    pub fn nice(&self) -> u64 { self.nice }
+    pub fn system(&self) -> u64 { self.system }
+    pub fn idle(&self) -> u64 { self.idle }
+    pub fn iowait(&self) -> u64 { self.iowait }
+    pub fn irq(&self) -> u64 { self.irq }
+    pub fn softirq(&self) -> u64 { self.softirq }
+    pub fn steal(&self) -> u64 { self.steal }
+    pub fn guest(&self) -> u64 { self.guest }
+    pub fn guest_nice(&self) -> u64 { self.guest_nice }
+    /// Columns after `guest_nice` that this version doesn't know about.
+    pub fn extra(&self) -> &[u64] { &self.extra }
+
     pub fn idle_time(&self) -> u64 { self.idle + self.iowait }
     // guest and guest_nice are already accounted for in user and nice
     pub fn user_time(&self) -> u64 { self.user + self.nice }
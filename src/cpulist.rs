@@ -0,0 +1,35 @@
+use crate::error::ParseError;
+
+/// Core numbers from here on are rejected, well above the kernel's
+/// `NR_CPUS`, so that a typo can't make a range allocate gigabytes.
+const MAX_CPUS: usize = 1 << 16;
+
+/// Parses the kernel's cpulist format, as used in sysfs and by `taskset`:
+/// comma-separated core numbers and inclusive ranges like `0-3,8,10-15`.
+///
+/// Cores are returned in the order given, and an empty list is valid.
+/// Core numbers must be below 65536.
+pub fn parse_cpulist(list: &str) -> Result<Vec<usize>, ParseError> {
+    let list = list.trim();
+    let bad = || ParseError::BadCpuList { line: list.to_owned() };
+    let number = |s: &str| match s.trim().parse::<usize>() {
+        Ok(idx) if idx < MAX_CPUS => Ok(idx),
+        _ => Err(bad())
+    };
+
+    let mut cpus = Vec::new();
+    for part in list.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.find('-') {
+            Some(dash) => {
+                let (first, last) = (number(&part[..dash])?, number(&part[dash + 1..])?);
+                if first > last {
+                    return Err(bad());
+                }
+                cpus.extend(first..=last);
+            },
+            None => cpus.push(number(part)?)
+        }
+    }
+
+    Ok(cpus)
+}
@@ -0,0 +1,86 @@
+use std::{
+    error,
+    fmt,
+    io
+};
+
+/// A line of procfs or sysfs that couldn't be understood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The line ended before the named field.
+    MissingField { field: &'static str, line: String },
+    /// The named field isn't an unsigned integer.
+    BadNumber { field: &'static str, line: String },
+    /// The `cpuN` label doesn't carry a valid core number.
+    BadCpuIndex { line: String },
+    /// Not a list of core numbers and ranges like `0-3,8`.
+    BadCpuList { line: String }
+}
+
+impl ParseError {
+    /// The offending line.
+    pub fn line(&self) -> &str {
+        match self {
+            ParseError::MissingField { line, .. }
+                | ParseError::BadNumber { line, .. }
+                | ParseError::BadCpuIndex { line }
+                | ParseError::BadCpuList { line } => line
+        }
+    }
+
+    pub(crate) fn with_line(mut self, full: &str) -> ParseError {
+        match &mut self {
+            ParseError::MissingField { line, .. }
+                | ParseError::BadNumber { line, .. }
+                | ParseError::BadCpuIndex { line }
+                | ParseError::BadCpuList { line } => *line = full.to_owned()
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingField { field, line } => write!(f, "missing field `{}` in {:?}", field, line),
+            ParseError::BadNumber { field, line } => write!(f, "bad number for `{}` in {:?}", field, line),
+            ParseError::BadCpuIndex { line } => write!(f, "bad cpu index in {:?}", line),
+            ParseError::BadCpuList { line } => write!(f, "bad cpu list {:?}", line)
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Everything that can go wrong while reading procfs or sysfs.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(ParseError)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Parse(e) => e.fmt(f)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error { Error::Io(e) }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error { Error::Parse(e) }
+}
@@ -0,0 +1,23 @@
+//! Per-core CPU load sampling from `/proc/stat`.
+//!
+//! Take two [`Stat`] readings some time apart and use [`Stat::load_since`]
+//! to get the [`Load`] of every core in between.
+
+extern crate vec_map;
+
+mod cpu;
+mod cpulist;
+mod error;
+mod presence;
+mod smooth;
+mod topology;
+mod uptime;
+
+pub use crate::cpu::{Stat, Load, CPU, PROC_ROOT};
+pub use crate::cpulist::parse_cpulist;
+pub use crate::error::{Error, ParseError};
+pub use crate::presence::Presence;
+pub use crate::smooth::Ema;
+pub use crate::topology::{CoreKind, CoreTopology, Topology, SYS_ROOT};
+pub use crate::uptime::read_uptime;
+pub use vec_map::VecMap;
@@ -2,16 +2,19 @@
 extern crate clap;
 
 use std::{
-    thread,
-    time::Duration
+    process,
+    time::{Duration, Instant}
 };
 
 use clap::{Arg, App};
-use cpu::*;
+use cpuline::{parse_cpulist, read_uptime, Ema, Presence, Stat, Topology, PROC_ROOT, SYS_ROOT};
+use color::{ColorBy, Markup, Palette, When};
+use output::{Format, GlyphSet, Grouping, Metric, Printer, Total};
+use schedule::Ticker;
 
-mod cpu;
-
-static FORMAT: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+mod color;
+mod output;
+mod schedule;
 
 fn main() {
     let matches = App::new("cpuline")
@@ -24,33 +27,376 @@ fn main() {
              .value_name("MS")
              .takes_value(true)
              .default_value("1000"))
+        .arg(Arg::with_name("count")
+             .short("n")
+             .long("count")
+             .value_name("N")
+             .help("Exit after printing N samples")
+             .takes_value(true))
+        .arg(Arg::with_name("once")
+             .short("1")
+             .long("once")
+             .help("Print a single sample, taken over one interval, and exit")
+             .conflicts_with("count"))
+        .arg(Arg::with_name("bootstrap")
+             .long("bootstrap")
+             .value_name("MS")
+             .help("Print the first sample after MS instead of a whole interval")
+             .takes_value(true))
+        .arg(Arg::with_name("since-boot")
+             .long("since-boot")
+             .help("Print the average load since boot and exit")
+             .conflicts_with_all(&["count", "once", "bootstrap", "smooth", "history"]))
+        .arg(Arg::with_name("align")
+             .long("align")
+             .help("Sample on multiples of the interval on the wall clock, e.g. on the second"))
+        .arg(Arg::with_name("proc-root")
+             .long("proc-root")
+             .value_name("DIR")
+             .help("Where procfs is mounted, e.g. /host/proc inside a container")
+             .takes_value(true)
+             .default_value(PROC_ROOT))
+        .arg(Arg::with_name("format")
+             .short("f")
+             .long("format")
+             .value_name("FORMAT")
+             .help("How to print each sample")
+             .takes_value(true)
+             .possible_values(Format::NAMES)
+             .default_value("glyphs"))
+        .arg(Arg::with_name("metrics")
+             .short("m")
+             .long("metrics")
+             .value_name("METRIC,...")
+             .help("Which percentages the json and csv formats print")
+             .takes_value(true)
+             .use_delimiter(true)
+             .possible_values(Metric::NAMES)
+             .default_value("busy,user,system,iowait,steal,idle"))
+        .arg(Arg::with_name("total")
+             .short("t")
+             .long("total")
+             .value_name("STYLE")
+             .help("Show the load of all cores together before the individual cores")
+             .takes_value(true)
+             .possible_values(Total::NAMES))
+        .arg(Arg::with_name("total-only")
+             .long("total-only")
+             .help("Show only the load of all cores together"))
+        .arg(Arg::with_name("color")
+             .long("color")
+             .value_name("WHEN")
+             .help("Color glyphs by load, auto only colors escape sequences on a terminal")
+             .takes_value(true)
+             .possible_values(When::NAMES)
+             .default_value("auto"))
+        .arg(Arg::with_name("markup")
+             .long("markup")
+             .value_name("MARKUP")
+             .help("How to write colors")
+             .takes_value(true)
+             .possible_values(Markup::NAMES)
+             .default_value("ansi"))
+        .arg(Arg::with_name("color-by")
+             .long("color-by")
+             .value_name("SCHEME")
+             .help("Color by how busy a core is, or by whether user, system, iowait or steal time dominates")
+             .takes_value(true)
+             .possible_values(ColorBy::NAMES)
+             .default_value("load"))
+        .arg(Arg::with_name("thresholds")
+             .long("thresholds")
+             .value_name("YELLOW,RED")
+             .help("Load percentages at which glyphs turn yellow and red")
+             .takes_value(true)
+             .use_delimiter(true)
+             .number_of_values(2)
+             .default_value("50,80"))
+        .arg(Arg::with_name("history")
+             .long("history")
+             .value_name("SAMPLES")
+             .help("Show a sparkline of the last SAMPLES samples per core, redrawn in place on a terminal")
+             .takes_value(true))
+        .arg(Arg::with_name("glyphs")
+             .short("g")
+             .long("glyphs")
+             .value_name("SET")
+             .help("Glyphs from idle to fully used: blocks, ascii, digits, shades, braille, or any string of glyphs")
+             .takes_value(true)
+             .default_value("blocks"))
+        .arg(Arg::with_name("height")
+             .long("height")
+             .value_name("ROWS")
+             .help("Draw each core as a vertical bar ROWS rows tall, redrawn in place on a terminal")
+             .takes_value(true)
+             .conflicts_with_all(&["history", "braille"]))
+        .arg(Arg::with_name("smooth")
+             .short("s")
+             .long("smooth")
+             .value_name("MS")
+             .help("Smooth the load of each core with an exponential moving average of this half-life")
+             .takes_value(true))
+        .arg(Arg::with_name("cpus")
+             .short("c")
+             .long("cpus")
+             .value_name("LIST")
+             .help("Show only these cores, in this order, e.g. 0-3,8,10-15")
+             .takes_value(true)
+             .validator(|list| parse_cpulist(&list).map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("group-by")
+             .long("group-by")
+             .value_name("GROUPING")
+             .help("Order cores by topology, with gaps between packages, NUMA nodes, physical cores or performance and efficiency cores")
+             .takes_value(true)
+             .possible_values(Grouping::NAMES))
+        .arg(Arg::with_name("merge-smt")
+             .long("merge-smt")
+             .help("Show one glyph per physical core, adding up its SMT siblings"))
+        .arg(Arg::with_name("sys-root")
+             .long("sys-root")
+             .value_name("DIR")
+             .help("Where sysfs is mounted")
+             .takes_value(true)
+             .default_value(SYS_ROOT))
+        .arg(Arg::with_name("offline-glyph")
+             .long("offline-glyph")
+             .value_name("GLYPH")
+             .help("Glyph in place of offline cores")
+             .takes_value(true)
+             .default_value("·"))
+        .arg(Arg::with_name("braille")
+             .long("braille")
+             .help("Pack two cores, or two samples of history, into each braille character"))
         .get_matches();
 
     let interval = value_t!(matches, "interval", u64).unwrap();
+    let proc_root = matches.value_of_os("proc-root").unwrap();
+    let format = value_t_or_exit!(matches, "format", Format);
+    let metrics = values_t_or_exit!(matches, "metrics", Metric);
+
+    let total = if matches.is_present("total") {
+        Some(value_t_or_exit!(matches, "total", Total))
+    } else {
+        None
+    };
+
+    let markup = value_t_or_exit!(matches, "markup", Markup);
+    let palette = if value_t_or_exit!(matches, "color", When).enabled(markup) {
+        let thresholds = values_t_or_exit!(matches, "thresholds", f64);
+        let by = value_t_or_exit!(matches, "color-by", ColorBy);
+        Some(Palette { markup, by, yellow: thresholds[0], red: thresholds[1] })
+    } else {
+        None
+    };
+
+    let sys_root = matches.value_of_os("sys-root").unwrap();
+    let group_by = if matches.is_present("group-by") {
+        Some(value_t_or_exit!(matches, "group-by", Grouping))
+    } else {
+        None
+    };
+    let merge_smt = matches.is_present("merge-smt");
+    let topology = if group_by.is_some() || merge_smt {
+        match Topology::read_in(sys_root) {
+            Ok(topology) => Some(topology),
+            Err(e) => {
+                eprintln!("cpuline: couldn't read topology: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut printer = Printer::new(format, metrics)
+        .total(total, matches.is_present("total-only"))
+        .palette(palette)
+        .history(if matches.is_present("history") { value_t_or_exit!(matches, "history", usize) } else { 0 })
+        .glyph_set(value_t_or_exit!(matches, "glyphs", GlyphSet))
+        .height(if matches.is_present("height") { value_t_or_exit!(matches, "height", usize) } else { 1 })
+        .cpus(matches.value_of("cpus").map(|list| parse_cpulist(list).unwrap()))
+        .topology(topology, group_by, merge_smt)
+        .offline_glyph(value_t_or_exit!(matches, "offline-glyph", char))
+        .braille(matches.is_present("braille"))
+        .in_place(atty::is(atty::Stream::Stdout));
+
+    let mut ema = if matches.is_present("smooth") {
+        Some(Ema::new(Duration::from_millis(value_t_or_exit!(matches, "smooth", u64))))
+    } else {
+        None
+    };
+
+    let count = if matches.is_present("once") {
+        Some(1)
+    } else if matches.is_present("count") {
+        Some(value_t_or_exit!(matches, "count", u64))
+    } else {
+        None
+    };
+
+    if matches.is_present("since-boot") {
+        match Stat::read_in(proc_root) {
+            Ok(stat) => {
+                printer.set_presence(Presence::read_in(sys_root).ok());
+                let uptime = read_uptime(proc_root).unwrap_or_default();
+                println!("{}", printer.render(&stat, &stat.since_boot(), uptime));
+            },
+            Err(e) => {
+                eprintln!("cpuline: couldn't read stat: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if count == Some(0) {
+        return;
+    }
+
+    let mut ticker = Ticker::new(Duration::from_millis(interval), matches.is_present("align"));
+    if matches.is_present("bootstrap") {
+        ticker.bootstrap(Duration::from_millis(value_t_or_exit!(matches, "bootstrap", u64)));
+    }
 
-    let mut stat = None;
+    let mut printed = 0;
+    let mut stat: Option<Reading> = None;
+    // Without sysfs, e.g. in some containers, only cores in /proc/stat are shown
+    let mut presence = Presence::read_in(sys_root).ok();
 
     loop {
         let old = stat;
-        stat = Stat::read().ok();
+        let new_presence = Presence::read_in(sys_root).ok();
+        if let (Some(old), Some(new)) = (&presence, &new_presence) {
+            log_hotplug(&mut printer, old, new);
+        }
+        presence = new_presence;
+        printer.set_presence(presence.clone());
 
-        match (&stat, &old) {
-            (&Some(ref now), &Some(ref old)) => {
-                let load = now.load_since(&old);
+        stat = match Stat::read_in(proc_root) {
+            Ok(stat) => Some(Reading {
+                stat,
+                at: Instant::now(),
+                // Without it suspend can't be told apart from a stall
+                uptime: read_uptime(proc_root).ok()
+            }),
+            Err(e) => {
+                log(&mut printer, &format!("couldn't read stat: {}", e));
+                // Scripts waiting for a bounded number of samples shouldn't hang
+                if count.is_some() {
+                    process::exit(1);
+                }
+                None
+            }
+        };
 
-                for (_, core) in load.cores.iter() {
-                    // How much this core was used with 0 (not used) to 1 (fully used)
-                    let used_part = core.busy_time() as f32 / core.total_time() as f32;
-                    let used_part = used_part.max(0.).min(1.);
+        if let (Some(now), Some(old)) = (&stat, &old) {
+            // Weigh by the time that actually passed, not the nominal interval
+            let elapsed = now.at.duration_since(old.at);
 
-                    let output = FORMAT[((FORMAT.len() - 1) as f32 * used_part) as usize];
-                    print!("{}", output);
+            if let Some(gap) = gap(old, now, Duration::from_millis(interval)) {
+                log(&mut printer, &format!("skipping sample after {}", gap));
+            } else {
+                let mut load = now.stat.load_since(&old.stat);
+                if let Some(ema) = &mut ema {
+                    load = ema.smooth(&load, elapsed);
                 }
-                println!("");
-            },
-            _ => ()
+
+                println!("{}", printer.render(&now.stat, &load, elapsed));
+
+                printed += 1;
+                if Some(printed) == count {
+                    return;
+                }
+            }
         }
 
-        thread::sleep(Duration::from_millis(interval));
+        ticker.wait();
+    }
+}
+
+/// Prints `message` to stderr, keeping it from being drawn over.
+fn log(printer: &mut Printer, message: &str) {
+    eprintln!("cpuline: {}", message);
+    // On the same terminal it moves the cursor below the sample being redrawn
+    if atty::is(atty::Stream::Stderr) {
+        printer.keep_drawn();
+    }
+}
+
+/// Reports cores that went offline or came online.
+fn log_hotplug(printer: &mut Printer, old: &Presence, new: &Presence) {
+    for idx in old.online.iter().filter(|idx| !new.online.contains(idx)) {
+        log(printer, &format!("cpu{} went offline", idx));
+    }
+    for idx in new.online.iter().filter(|idx| !old.online.contains(idx)) {
+        log(printer, &format!("cpu{} came online", idx));
+    }
+}
+
+struct Reading {
+    stat: Stat,
+    /// Monotonic, so it stops during suspend
+    at: Instant,
+    /// Since boot, including suspend
+    uptime: Option<Duration>
+}
+
+/// Leeway for timer and /proc/uptime resolution when looking for gaps
+const GAP_SLACK: Duration = Duration::from_millis(50);
+
+/// Describes why the time between `old` and `now` is too long to make a
+/// meaningful sample of an `interval`, if it is.
+fn gap(old: &Reading, now: &Reading, interval: Duration) -> Option<String> {
+    let monotonic = now.at.duration_since(old.at);
+    let boot = match (old.uptime, now.uptime) {
+        (Some(old), Some(now)) => now.saturating_sub(old),
+        _ => monotonic
+    };
+
+    // A late wakeup can stretch a sample to almost twice the interval, and
+    // /proc/uptime only has a resolution of 10ms
+    if boot <= interval * 2 + GAP_SLACK {
+        return None;
+    }
+
+    let suspended = boot.saturating_sub(monotonic);
+    Some(if suspended > GAP_SLACK {
+        format!("{:.1}s of suspend", suspended.as_secs_f64())
+    } else {
+        format!("a {:.1}s stall", boot.as_secs_f64())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(at: Instant, uptime: Duration) -> Reading {
+        Reading { stat: Stat::read_from(&b""[..]).unwrap(), at, uptime: Some(uptime) }
+    }
+
+    #[test]
+    fn normal_sample_is_no_gap() {
+        let start = Instant::now();
+        let old = reading(start, Duration::from_secs(100));
+        let now = reading(start + Duration::from_millis(130), Duration::from_millis(100_130));
+        assert_eq!(gap(&old, &now, Duration::from_millis(100)), None);
+    }
+
+    #[test]
+    fn stall_is_a_gap() {
+        let start = Instant::now();
+        let old = reading(start, Duration::from_secs(100));
+        let now = reading(start + Duration::from_millis(1200), Duration::from_millis(101_200));
+        assert_eq!(gap(&old, &now, Duration::from_millis(100)).unwrap(), "a 1.2s stall");
+    }
+
+    #[test]
+    fn suspend_is_a_gap() {
+        let start = Instant::now();
+        let old = reading(start, Duration::from_secs(100));
+        // The monotonic clock stands still during suspend, the boot clock doesn't
+        let now = reading(start + Duration::from_millis(1000), Duration::from_secs(161));
+        assert_eq!(gap(&old, &now, Duration::from_secs(1)).unwrap(), "60.0s of suspend");
     }
 }
@@ -0,0 +1,904 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use cpuline::{Load, Presence, Stat, Topology, CPU};
+
+use crate::color::{Canvas, Color, Palette};
+
+/// Glyphs for increasing load, from idle to fully used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlyphSet(Vec<char>);
+
+impl GlyphSet {
+    pub fn blocks() -> GlyphSet {
+        GlyphSet("▁▂▃▄▅▆▇█".chars().collect())
+    }
+
+    /// The glyph for `used_part` between 0 (not used) and 1 (fully used).
+    fn quantize(&self, used_part: f32) -> char {
+        self.0[((self.0.len() - 1) as f32 * used_part) as usize]
+    }
+
+    /// The glyph for `row` (0 at the bottom) of a bar `height` rows tall
+    /// filled to `used_part`, each row adding as many levels as there are
+    /// glyphs.
+    fn bar_row(&self, used_part: f32, row: usize, height: usize) -> char {
+        let levels = self.0.len();
+        let filled = (used_part * (height * levels) as f32).round() as usize;
+        match filled.saturating_sub(row * levels) {
+            0 => ' ',
+            level if level >= levels => self.0[levels - 1],
+            level => self.0[level - 1]
+        }
+    }
+}
+
+impl FromStr for GlyphSet {
+    type Err = String;
+
+    /// One of the built-in sets by name, or any string of at least two
+    /// glyphs to use as-is.
+    fn from_str(s: &str) -> Result<GlyphSet, String> {
+        let glyphs = match s {
+            "blocks" => return Ok(GlyphSet::blocks()),
+            "ascii" => "_.-=#",
+            "digits" => "0123456789",
+            "shades" => " ░▒▓█",
+            "braille" => "⣀⣤⣶⣿",
+            custom => custom
+        };
+
+        let glyphs: Vec<char> = glyphs.chars().collect();
+        if glyphs.len() < 2 {
+            return Err(format!("{:?} is neither a glyph set nor at least two glyphs", s));
+        }
+        Ok(GlyphSet(glyphs))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One block glyph per core
+    Glyphs,
+    /// One JSON object per sample, for jq and log shippers
+    Json,
+    /// One row per sample, preceded by a header whenever the cores change
+    Csv
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &["glyphs", "json", "csv"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "glyphs" => Ok(Format::Glyphs),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown format {:?}", s))
+        }
+    }
+}
+
+/// A percentage derived from a core's load, for the structured formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Busy,
+    User,
+    System,
+    Iowait,
+    Steal,
+    Idle
+}
+
+impl Metric {
+    pub const NAMES: &'static [&'static str] = &["busy", "user", "system", "iowait", "steal", "idle"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Busy => "busy",
+            Metric::User => "user",
+            Metric::System => "system",
+            Metric::Iowait => "iowait",
+            Metric::Steal => "steal",
+            Metric::Idle => "idle"
+        }
+    }
+
+    /// Percentage of `core`s total time spent in this state, 0 if no time passed.
+    pub fn percent(self, core: &CPU) -> f64 {
+        let part = match self {
+            Metric::Busy => core.busy_time(),
+            Metric::User => core.user_time(),
+            Metric::System => core.system_time(),
+            Metric::Iowait => core.iowait(),
+            Metric::Steal => core.steal(),
+            Metric::Idle => core.idle()
+        };
+
+        match core.total_time() {
+            0 => 0.,
+            total => 100. * part as f64 / total as f64
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "busy" => Ok(Metric::Busy),
+            "user" => Ok(Metric::User),
+            "system" => Ok(Metric::System),
+            "iowait" => Ok(Metric::Iowait),
+            "steal" => Ok(Metric::Steal),
+            "idle" => Ok(Metric::Idle),
+            _ => Err(format!("unknown metric {:?}", s))
+        }
+    }
+}
+
+/// How the glyph format shows the aggregate of all cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Total {
+    /// A glyph of its own, before the cores
+    Glyph,
+    /// A percentage, before the cores
+    Percent
+}
+
+impl Total {
+    pub const NAMES: &'static [&'static str] = &["glyph", "percent"];
+}
+
+impl FromStr for Total {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Total, String> {
+        match s {
+            "glyph" => Ok(Total::Glyph),
+            "percent" => Ok(Total::Percent),
+            _ => Err(format!("unknown total style {:?}", s))
+        }
+    }
+}
+
+/// Which cores go together when grouping by topology.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Package,
+    Node,
+    /// SMT siblings of the same physical core
+    Core,
+    /// Performance and efficiency cores of hybrid CPUs
+    Kind
+}
+
+impl Grouping {
+    pub const NAMES: &'static [&'static str] = &["package", "node", "core", "kind"];
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Grouping, String> {
+        match s {
+            "package" | "socket" => Ok(Grouping::Package),
+            "node" => Ok(Grouping::Node),
+            "core" => Ok(Grouping::Core),
+            "kind" => Ok(Grouping::Kind),
+            _ => Err(format!("unknown grouping {:?}", s))
+        }
+    }
+}
+
+/// A place in the layout of cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Core(usize),
+    /// A core that is present, but offline
+    Offline(usize),
+    /// Separates groups of cores
+    Gap
+}
+
+/// The cores in `columns`, online or not, without gaps.
+fn cores(columns: &[Column]) -> impl Iterator<Item = usize> + '_ {
+    columns.iter().filter_map(|column| match column {
+        Column::Core(idx) | Column::Offline(idx) => Some(*idx),
+        Column::Gap => None
+    })
+}
+
+/// Turns samples into lines of output, remembering what earlier lines need
+/// to stay consistent with.
+pub struct Printer {
+    format: Format,
+    metrics: Vec<Metric>,
+    total: Option<Total>,
+    total_only: bool,
+    palette: Option<Palette>,
+    /// How many samples the sparklines span, 0 to print only the latest
+    history: usize,
+    past: VecDeque<Load>,
+    glyph_set: GlyphSet,
+    /// Rows per core, for vertical bars
+    height: usize,
+    /// Pack two cores, or two samples of history, into each character
+    braille: bool,
+    /// Whether multi-line output overwrites the previous sample
+    in_place: bool,
+    /// Cores to show, in order, instead of all of them
+    cpus: Option<Vec<usize>>,
+    topology: Option<Topology>,
+    group_by: Option<Grouping>,
+    /// Show one glyph per physical core instead of per SMT sibling
+    merge_smt: bool,
+    /// The CPUs at the time of the latest sample
+    presence: Option<Presence>,
+    offline_glyph: char,
+    /// Lines printed for the previous sample
+    drawn: usize,
+    /// Cores named by the last CSV header
+    header: Option<Vec<usize>>
+}
+
+impl Printer {
+    pub fn new(format: Format, metrics: Vec<Metric>) -> Printer {
+        Printer { format, metrics, total: None, total_only: false, palette: None,
+                  history: 0, past: VecDeque::new(), glyph_set: GlyphSet::blocks(),
+                  height: 1, braille: false, in_place: false, cpus: None,
+                  topology: None, group_by: None, merge_smt: false,
+                  presence: None, offline_glyph: '·', drawn: 0, header: None }
+    }
+
+    /// Show the aggregate of all cores in the glyph format, optionally
+    /// instead of the individual cores.
+    pub fn total(mut self, total: Option<Total>, only: bool) -> Printer {
+        self.total = total.or(if only { Some(Total::Glyph) } else { None });
+        self.total_only = only;
+        self
+    }
+
+    /// Color glyphs by load, or not at all if `palette` is `None`.
+    pub fn palette(mut self, palette: Option<Palette>) -> Printer {
+        self.palette = palette;
+        self
+    }
+
+    /// Print a sparkline of the last `samples` samples per core instead
+    /// of a single glyph.
+    pub fn history(mut self, samples: usize) -> Printer {
+        self.history = samples;
+        self
+    }
+
+    pub fn glyph_set(mut self, glyph_set: GlyphSet) -> Printer {
+        self.glyph_set = glyph_set;
+        self
+    }
+
+    /// Draw each core as a vertical bar `rows` rows tall.
+    pub fn height(mut self, rows: usize) -> Printer {
+        self.height = rows;
+        self
+    }
+
+    /// Draw braille characters with two bars of four dots each, halving
+    /// the width.
+    pub fn braille(mut self, braille: bool) -> Printer {
+        self.braille = braille;
+        self
+    }
+
+    /// Show only `cpus`, in that order, keeping a blank in place of
+    /// those that don't exist.
+    pub fn cpus(mut self, cpus: Option<Vec<usize>>) -> Printer {
+        self.cpus = cpus;
+        self
+    }
+
+    /// Order cores by `topology` with gaps between groups, and optionally
+    /// add up SMT siblings.
+    pub fn topology(mut self, topology: Option<Topology>, group_by: Option<Grouping>, merge_smt: bool) -> Printer {
+        self.topology = topology;
+        self.group_by = group_by;
+        self.merge_smt = merge_smt;
+        self
+    }
+
+    /// The glyph in place of offline cores.
+    pub fn offline_glyph(mut self, glyph: char) -> Printer {
+        self.offline_glyph = glyph;
+        self
+    }
+
+    /// Lay out all present cores, including offline ones, as of the next
+    /// sample. Without it, only cores in the latest reading are shown.
+    pub fn set_presence(&mut self, presence: Option<Presence>) {
+        self.presence = presence;
+    }
+
+    /// Redraw multi-line output in place with cursor movement, instead of
+    /// printing below the previous sample.
+    pub fn in_place(mut self, in_place: bool) -> Printer {
+        self.in_place = in_place;
+        self
+    }
+
+    /// Leaves the previous multi-line output alone, so the next sample is
+    /// drawn below whatever was printed after it.
+    pub fn keep_drawn(&mut self) {
+        self.drawn = 0;
+    }
+
+    /// Renders one sample, `now` being the later of the two readings in
+    /// `load`, taken `elapsed` after the earlier one.
+    pub fn render(&mut self, now: &Stat, load: &Load, elapsed: Duration) -> String {
+        let merged;
+        let load = match &self.topology {
+            Some(topology) if self.merge_smt => {
+                merged = topology.merge_siblings(load);
+                &merged
+            },
+            _ => load
+        };
+
+        match self.format {
+            Format::Glyphs if self.history > 0 => {
+                if self.past.len() == self.history {
+                    self.past.pop_front();
+                }
+                self.past.push_back(load.clone());
+
+                let out = self.sparklines(now);
+                self.redraw(out)
+            },
+            Format::Glyphs if self.height > 1 => {
+                let out = self.bars(now, load);
+                self.redraw(out)
+            },
+            Format::Glyphs => self.glyphs(now, load),
+            Format::Json => self.json(now, load, elapsed),
+            Format::Csv => self.csv(now, load)
+        }
+    }
+
+    /// Moves the cursor back over the previous output, so `out` replaces it.
+    fn redraw(&mut self, out: String) -> String {
+        if !self.in_place {
+            return out;
+        }
+
+        let mut redrawn = String::new();
+        if self.drawn > 0 {
+            write!(redrawn, "\x1b[{}F", self.drawn).unwrap();
+        }
+        // Clear what's left of longer lines, and of more lines, from before
+        redrawn.push_str(&out.replace('\n', "\x1b[K\n"));
+        redrawn.push_str("\x1b[K\x1b[J");
+
+        self.drawn = out.lines().count();
+        redrawn
+    }
+
+    /// Draws one glyph per cell, or one braille character per two cells.
+    fn push_cells(&self, canvas: &mut Canvas, cells: &[Option<&CPU>]) {
+        if !self.braille {
+            for &core in cells {
+                canvas.push(self.color(core), self.glyph(core));
+            }
+            return;
+        }
+
+        for pair in cells.chunks(2) {
+            let (left, right) = (pair[0], pair.get(1).copied().flatten());
+            // A character has only one color, that of the busier half
+            let busier = if used_part(right) > used_part(left) { right } else { left };
+            canvas.push(self.color(busier), braille(left, right));
+        }
+    }
+
+    /// The glyph for how much `core` was used, a blank for no sample.
+    fn glyph(&self, core: Option<&CPU>) -> char {
+        match core {
+            Some(_) => self.glyph_set.quantize(used_part(core)),
+            None => ' '
+        }
+    }
+
+    /// The cores to show, in order.
+    fn columns(&self, now: &Stat) -> Vec<Column> {
+        let mut cpus = match (&self.cpus, &self.presence) {
+            (Some(cpus), _) => cpus.clone(),
+            (None, Some(presence)) => presence.present.clone(),
+            (None, None) => now.cores().keys().collect()
+        };
+
+        let column = |idx| match &self.presence {
+            Some(presence) if presence.is_offline(idx) => Column::Offline(idx),
+            _ => Column::Core(idx)
+        };
+
+        let topology = match &self.topology {
+            Some(topology) => topology,
+            None => return cpus.into_iter().map(column).collect()
+        };
+
+        if self.merge_smt {
+            let mut seen = Vec::new();
+            cpus = cpus.into_iter()
+                .map(|idx| topology.first_sibling(idx))
+                .filter(|idx| if seen.contains(idx) { false } else { seen.push(*idx); true })
+                .collect();
+        }
+
+        let group_by = match self.group_by {
+            // Merged siblings leave a single core per group, so gaps would only add width
+            Some(Grouping::Core) if self.merge_smt => return cpus.into_iter().map(column).collect(),
+            Some(group_by) => group_by,
+            None => return cpus.into_iter().map(column).collect()
+        };
+
+        // Cores without topology go last, in their own group
+        let group = |idx: usize| topology.cpu(idx).map(|cpu| match group_by {
+            Grouping::Package => (cpu.package, 0),
+            // Packages of CPUs without a node are grouped apart from nodes
+            Grouping::Node => (usize::from(cpu.node.is_none()), cpu.node.unwrap_or(cpu.package)),
+            Grouping::Core => (cpu.package, cpu.core),
+            // Performance cores first, then efficiency cores, then unknown ones
+            Grouping::Kind => (cpu.kind.map_or(2, |kind| kind as usize), 0)
+        });
+        let order = |idx: usize| {
+            let cpu = topology.cpu(idx);
+            (group(idx).is_none(), group(idx), cpu.map(|cpu| (cpu.package, cpu.core)))
+        };
+        cpus.sort_by_key(|&idx| order(idx));
+
+        let mut columns = Vec::new();
+        for (i, &idx) in cpus.iter().enumerate() {
+            if i > 0 && group(cpus[i - 1]) != group(idx) {
+                columns.push(Column::Gap);
+            }
+            columns.push(column(idx));
+        }
+        columns
+    }
+
+    fn color(&self, core: Option<&CPU>) -> Option<Color> {
+        let palette = self.palette?;
+        core.and_then(|core| palette.for_core(core))
+    }
+
+    /// One line per core (and the total) with the glyphs of past samples,
+    /// oldest first and right-aligned to keep a fixed width.
+    fn sparklines(&self, now: &Stat) -> String {
+        let mut rows = Vec::new();
+        if self.total.is_some() {
+            rows.push(None);
+        }
+        if !self.total_only {
+            rows.extend(self.columns(now).into_iter().map(Some));
+        }
+
+        let mut canvas = Canvas::new(self.palette.map(|p| p.markup));
+        for (i, row) in rows.into_iter().enumerate() {
+            if i > 0 {
+                canvas.push(None, '\n');
+            }
+
+            // Groups are separated by an empty line
+            if row == Some(Column::Gap) {
+                continue;
+            }
+
+            // The latest total percentage goes before its sparkline, and
+            // blanks before the others to keep them aligned
+            if self.total == Some(Total::Percent) {
+                match (row, self.past.back().and_then(|load| load.total.as_ref())) {
+                    (None, Some(total)) => canvas.push_str(self.color(Some(total)), &format!("{:3.0}%", Metric::Busy.percent(total))),
+                    _ => canvas.push_str(None, "    ")
+                }
+                canvas.push(None, ' ');
+            }
+
+            let padding = (self.past.len()..self.history).map(|_| None);
+            let cells: Vec<_> = padding.chain(self.past.iter().map(|load| match row {
+                Some(Column::Core(idx)) | Some(Column::Offline(idx)) => load.cores.get(idx),
+                _ => load.total.as_ref()
+            })).collect();
+
+            match row {
+                // Fill the time since going offline with placeholders
+                Some(Column::Offline(_)) if !self.braille => {
+                    let filled = cells.len() - cells.iter().rev().take_while(|core| core.is_none()).count();
+                    self.push_cells(&mut canvas, &cells[..filled]);
+                    for _ in filled..cells.len() {
+                        canvas.push(None, self.offline_glyph);
+                    }
+                },
+                _ => self.push_cells(&mut canvas, &cells)
+            }
+        }
+
+        canvas.finish()
+    }
+
+    fn glyphs(&self, now: &Stat, load: &Load) -> String {
+        let mut canvas = Canvas::new(self.palette.map(|p| p.markup));
+        let color = |core| self.color(core);
+
+        match self.total {
+            Some(Total::Glyph) => canvas.push(color(load.total.as_ref()), self.glyph(load.total.as_ref())),
+            Some(Total::Percent) => match &load.total {
+                Some(total) => canvas.push_str(color(Some(total)), &format!("{:3.0}%", Metric::Busy.percent(total))),
+                None => canvas.push_str(None, "    ")
+            },
+            None => ()
+        }
+
+        if !self.total_only {
+            if self.total.is_some() {
+                canvas.push(None, ' ');
+            }
+
+            // Keep the remaining glyphs in place for cores without a sample
+            let columns = self.columns(now);
+            for (i, group) in columns.split(|column| *column == Column::Gap).enumerate() {
+                if i > 0 {
+                    canvas.push(None, ' ');
+                }
+
+                let mut cells = Vec::new();
+                for column in group {
+                    match column {
+                        // Braille has no room for a placeholder, so it stays blank
+                        Column::Offline(_) if !self.braille => {
+                            self.push_cells(&mut canvas, &cells);
+                            cells.clear();
+                            canvas.push(None, self.offline_glyph);
+                        },
+                        Column::Core(idx) | Column::Offline(idx) => cells.push(load.cores.get(*idx)),
+                        Column::Gap => ()
+                    }
+                }
+                self.push_cells(&mut canvas, &cells);
+            }
+        }
+
+        canvas.finish()
+    }
+
+    /// Vertical bars `height` rows tall, with the total percentage on the
+    /// bottom row.
+    fn bars(&self, now: &Stat, load: &Load) -> String {
+        let columns = self.columns(now);
+        let mut canvas = Canvas::new(self.palette.map(|p| p.markup));
+        let cell = |canvas: &mut Canvas, core: Option<&CPU>, row| match core {
+            Some(_) => canvas.push(self.color(core), self.glyph_set.bar_row(used_part(core), row, self.height)),
+            None => canvas.push(None, ' ')
+        };
+
+        for row in (0..self.height).rev() {
+            match (self.total, &load.total) {
+                (Some(Total::Glyph), total) => cell(&mut canvas, total.as_ref(), row),
+                (Some(Total::Percent), Some(total)) if row == 0 =>
+                    canvas.push_str(self.color(Some(total)), &format!("{:3.0}%", Metric::Busy.percent(total))),
+                (Some(Total::Percent), _) => canvas.push_str(None, "    "),
+                (None, _) => ()
+            }
+
+            if !self.total_only {
+                if self.total.is_some() {
+                    canvas.push(None, ' ');
+                }
+                for column in &columns {
+                    match column {
+                        Column::Core(idx) => cell(&mut canvas, load.cores.get(*idx), row),
+                        Column::Offline(_) if row == 0 => canvas.push(None, self.offline_glyph),
+                        Column::Offline(_) | Column::Gap => canvas.push(None, ' ')
+                    }
+                }
+            }
+
+            if row > 0 {
+                canvas.push(None, '\n');
+            }
+        }
+
+        canvas.finish()
+    }
+
+    fn json_breakdown(&self, out: &mut String, core: &CPU) {
+        for (i, metric) in self.metrics.iter().enumerate() {
+            if i > 0 { out.push(','); }
+            write!(out, "\"{}\":{:.2}", metric.name(), metric.percent(core)).unwrap();
+        }
+    }
+
+    fn json(&self, now: &Stat, load: &Load, elapsed: Duration) -> String {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut out = format!("{{\"timestamp\":{}.{:03},\"elapsed\":{:.3},\"total\":",
+                              timestamp.as_secs(), timestamp.subsec_millis(), elapsed.as_secs_f64());
+
+        match &load.total {
+            Some(total) => {
+                out.push('{');
+                self.json_breakdown(&mut out, total);
+                out.push('}');
+            },
+            None => out.push_str("null")
+        }
+
+        out.push_str(",\"cores\":[");
+        let columns = self.columns(now);
+        let shown = cores(&columns).filter_map(|idx| load.cores.get(idx).map(|core| (idx, core)));
+        for (i, (idx, core)) in shown.enumerate() {
+            if i > 0 { out.push(','); }
+            write!(out, "{{\"cpu\":{}", idx).unwrap();
+            if !self.metrics.is_empty() { out.push(','); }
+            self.json_breakdown(&mut out, core);
+            out.push('}');
+        }
+
+        let offline: Vec<_> = columns.iter().filter_map(|column| match column {
+            Column::Offline(idx) => Some(*idx),
+            _ => None
+        }).collect();
+
+        for (name, list) in &[("invalid", &load.invalid), ("offline", &offline)] {
+            write!(out, "],\"{}\":[", name).unwrap();
+            for (i, idx) in list.iter().enumerate() {
+                if i > 0 { out.push(','); }
+                write!(out, "{}", idx).unwrap();
+            }
+        }
+        out.push_str("]}");
+
+        out
+    }
+
+    fn csv(&mut self, now: &Stat, load: &Load) -> String {
+        let mut out = String::new();
+
+        // Columns follow the cores of the newest reading, so a core that
+        // comes or goes starts a new header instead of shifting the columns
+        let cores: Vec<_> = cores(&self.columns(now)).collect();
+        if self.header.as_ref() != Some(&cores) {
+            out.push_str("timestamp");
+            for idx in &cores {
+                for metric in &self.metrics {
+                    write!(out, ",cpu{}_{}", idx, metric.name()).unwrap();
+                }
+            }
+            out.push('\n');
+            self.header = Some(cores);
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(out, "{}.{:03}", timestamp.as_secs(), timestamp.subsec_millis()).unwrap();
+        for &idx in self.header.as_ref().unwrap() {
+            let core = load.cores.get(idx);
+            for metric in &self.metrics {
+                out.push(',');
+                // Cores without a sample get empty cells
+                if let Some(core) = core {
+                    write!(out, "{:.2}", metric.percent(core)).unwrap();
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// How much `core` was used with 0 (not used) to 1 (fully used), 0 for no sample.
+fn used_part(core: Option<&CPU>) -> f32 {
+    match core {
+        Some(core) => (core.busy_time() as f32 / core.total_time() as f32).clamp(0., 1.),
+        None => 0.
+    }
+}
+
+/// Dots of the left and right braille columns, bottom to top.
+static BRAILLE_LEFT: [u32; 4] = [0x40, 0x04, 0x02, 0x01];
+static BRAILLE_RIGHT: [u32; 4] = [0x80, 0x20, 0x10, 0x08];
+
+/// A braille character with bars for `left` and `right`, 0 to 4 dots high.
+fn braille(left: Option<&CPU>, right: Option<&CPU>) -> char {
+    let dots = |core, column: &[u32; 4]| {
+        let level = (used_part(core) * column.len() as f32).round() as usize;
+        column[..level].iter().sum::<u32>()
+    };
+
+    std::char::from_u32(0x2800 + dots(left, &BRAILLE_LEFT) + dots(right, &BRAILLE_RIGHT)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reading of `/proc/stat` lines, with the load since boot.
+    fn sample(stat: &str) -> (Stat, Load) {
+        let stat = Stat::read_from(stat.as_bytes()).unwrap();
+        let load = stat.since_boot();
+        (stat, load)
+    }
+
+    /// A core that spent `busy` of 100 ticks busy.
+    fn core(busy: u64) -> CPU {
+        CPU::from_line(&format!("{} 0 0 {}", busy, 100 - busy)).unwrap()
+    }
+
+    fn sysfs(name: &str) -> std::path::PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "sysfs", name].iter().collect()
+    }
+
+    /// The CSV lines of `out`, with the timestamps of rows left out.
+    fn untimed(out: &str) -> Vec<&str> {
+        out.lines()
+            .map(|line| if line.starts_with("timestamp") { line } else { &line[line.find(',').unwrap()..] })
+            .collect()
+    }
+
+    /// The JSON object in `out` from after the timestamp.
+    fn untimed_json(out: &str) -> &str {
+        assert!(out.starts_with("{\"timestamp\":"));
+        &out[out.find(",\"elapsed\"").unwrap()..]
+    }
+
+    #[test]
+    fn json_shape() {
+        let mut printer = Printer::new(Format::Json, vec![Metric::Busy, Metric::Idle]);
+        let (stat, load) = sample("cpu  50 0 0 150\ncpu0 50 0 0 50\ncpu1 0 0 0 100\n");
+
+        let out = printer.render(&stat, &load, Duration::from_millis(1500));
+        assert_eq!(untimed_json(&out), concat!(",\"elapsed\":1.500,\"total\":{\"busy\":25.00,\"idle\":75.00},",
+                                               "\"cores\":[{\"cpu\":0,\"busy\":50.00,\"idle\":50.00},{\"cpu\":1,\"busy\":0.00,\"idle\":100.00}],",
+                                               "\"invalid\":[],\"offline\":[]}"));
+    }
+
+    #[test]
+    fn json_lists_invalid_and_offline_cores() {
+        let mut printer = Printer::new(Format::Json, Vec::new());
+        printer.set_presence(Some(Presence { present: vec![0, 1, 2], online: vec![0, 1] }));
+        let before = Stat::read_from("cpu  100 0 0 100\ncpu0 10 0 0 10\ncpu1 50 0 0 50\n".as_bytes()).unwrap();
+        // The total and cpu1 went backwards
+        let now = Stat::read_from("cpu  90 0 0 100\ncpu0 20 0 0 20\ncpu1 40 0 0 50\n".as_bytes()).unwrap();
+
+        let out = printer.render(&now, &now.load_since(&before), Duration::from_secs(1));
+        assert_eq!(untimed_json(&out), ",\"elapsed\":1.000,\"total\":null,\"cores\":[{\"cpu\":0}],\"invalid\":[1],\"offline\":[2]}");
+    }
+
+    #[test]
+    fn csv_header_follows_cores() {
+        let mut printer = Printer::new(Format::Csv, vec![Metric::Busy, Metric::Idle]);
+        let (stat, load) = sample("cpu0 50 0 0 50\ncpu1 0 0 0 100\n");
+
+        let out = printer.render(&stat, &load, Duration::from_secs(1));
+        assert_eq!(untimed(&out), ["timestamp,cpu0_busy,cpu0_idle,cpu1_busy,cpu1_idle", ",50.00,50.00,0.00,100.00"]);
+        let out = printer.render(&stat, &load, Duration::from_secs(1));
+        assert_eq!(untimed(&out), [",50.00,50.00,0.00,100.00"]);
+
+        let (stat, load) = sample("cpu0 50 0 0 50\n");
+        let out = printer.render(&stat, &load, Duration::from_secs(1));
+        assert_eq!(untimed(&out), ["timestamp,cpu0_busy,cpu0_idle", ",50.00,50.00"]);
+    }
+
+    #[test]
+    fn csv_leaves_missing_cores_empty() {
+        let mut printer = Printer::new(Format::Csv, vec![Metric::Busy]).cpus(Some(vec![1, 5, 0]));
+        let (stat, load) = sample("cpu0 50 0 0 50\ncpu1 25 0 0 75\n");
+
+        let out = printer.render(&stat, &load, Duration::from_secs(1));
+        assert_eq!(untimed(&out), ["timestamp,cpu1_busy,cpu5_busy,cpu0_busy", ",25.00,,50.00"]);
+    }
+
+    #[test]
+    fn braille_dots_follow_load() {
+        let (idle, half, full) = (core(0), core(50), core(100));
+        assert_eq!(braille(None, None), '\u{2800}');
+        assert_eq!(braille(Some(&idle), Some(&idle)), '\u{2800}');
+        assert_eq!(braille(Some(&full), Some(&full)), '\u{28FF}');
+        // The bottom two dots of each column
+        assert_eq!(braille(Some(&half), None), '\u{2844}');
+        assert_eq!(braille(None, Some(&half)), '\u{28A0}');
+        assert_eq!(braille(Some(&half), Some(&full)), '\u{28FC}');
+    }
+
+    #[test]
+    fn glyph_sets_by_name_or_glyphs() {
+        let glyphs = |s: &str| s.parse::<GlyphSet>().map(|set| set.0.into_iter().collect::<String>());
+        assert_eq!(glyphs("blocks").unwrap(), "▁▂▃▄▅▆▇█");
+        assert_eq!(glyphs("ascii").unwrap(), "_.-=#");
+        assert_eq!(glyphs("digits").unwrap(), "0123456789");
+        assert_eq!(glyphs("shades").unwrap(), " ░▒▓█");
+        assert_eq!(glyphs("braille").unwrap(), "⣀⣤⣶⣿");
+        assert_eq!(glyphs("oO").unwrap(), "oO");
+        assert!(glyphs("o").is_err());
+        assert!(glyphs("").is_err());
+    }
+
+    #[test]
+    fn quantize_spans_the_whole_set() {
+        let ascii: GlyphSet = "ascii".parse().unwrap();
+        assert_eq!([ascii.quantize(0.), ascii.quantize(0.5), ascii.quantize(1.)], ['_', '-', '#']);
+        let digits: GlyphSet = "digits".parse().unwrap();
+        assert_eq!([digits.quantize(0.), digits.quantize(0.5), digits.quantize(1.)], ['0', '4', '9']);
+    }
+
+    #[test]
+    fn bar_rows_fill_bottom_up() {
+        let blocks = GlyphSet::blocks();
+        let rows = |used_part: f32| [blocks.bar_row(used_part, 0, 2), blocks.bar_row(used_part, 1, 2)];
+        assert_eq!(rows(0.), [' ', ' ']);
+        assert_eq!(rows(1. / 16.), ['▁', ' ']);
+        assert_eq!(rows(7. / 16.), ['▇', ' ']);
+        // A full bottom row leaves nothing for the one above
+        assert_eq!(rows(0.5), ['█', ' ']);
+        assert_eq!(rows(9. / 16.), ['█', '▁']);
+        assert_eq!(rows(1.), ['█', '█']);
+    }
+
+    #[test]
+    fn columns_group_by_topology() {
+        use Column::{Core, Gap};
+
+        let topology = Topology::read_in(sysfs("smt-2x2x2")).unwrap();
+        let (stat, _) = sample("cpu0 0 0 0 0\n");
+        let printer = |group_by, merge_smt| Printer::new(Format::Glyphs, Vec::new())
+            .cpus(Some(vec![8, 7, 6, 5, 4, 3, 2, 1, 0]))
+            .topology(Some(topology.clone()), group_by, merge_smt);
+
+        assert_eq!(printer(Some(Grouping::Package), false).columns(&stat),
+                   [Core(4), Core(0), Core(5), Core(1), Gap, Core(6), Core(2), Core(7), Core(3), Gap, Core(8)]);
+        assert_eq!(printer(Some(Grouping::Core), false).columns(&stat),
+                   [Core(4), Core(0), Gap, Core(5), Core(1), Gap, Core(6), Core(2), Gap, Core(7), Core(3), Gap, Core(8)]);
+        // Without grouping, or with one core per group, the order given stays
+        assert_eq!(printer(None, true).columns(&stat), [Core(8), Core(3), Core(2), Core(1), Core(0)]);
+        assert_eq!(printer(Some(Grouping::Core), true).columns(&stat), [Core(8), Core(3), Core(2), Core(1), Core(0)]);
+    }
+
+    #[test]
+    fn sparklines_fill_offline_cores() {
+        let mut printer = Printer::new(Format::Glyphs, Vec::new()).history(3).offline_glyph('x');
+        let (stat, load) = sample("cpu0 0 0 0 100\ncpu1 100 0 0 0\n");
+        printer.render(&stat, &load, Duration::from_secs(1));
+
+        printer.set_presence(Some(Presence { present: vec![0, 1], online: vec![0] }));
+        let (stat, load) = sample("cpu0 0 0 0 100\n");
+        assert_eq!(printer.render(&stat, &load, Duration::from_secs(1)), " ▁▁\n █x");
+    }
+
+    #[test]
+    fn redraws_over_kept_lines_only() {
+        let mut printer = Printer::new(Format::Glyphs, Vec::new()).history(2).in_place(true);
+        let (stat, load) = sample("cpu0 0 0 0 100\ncpu1 100 0 0 0\n");
+
+        assert!(!printer.render(&stat, &load, Duration::from_secs(1)).contains("\x1b[2F"));
+        assert!(printer.render(&stat, &load, Duration::from_secs(1)).starts_with("\x1b[2F"));
+        // Something was printed below, so the last sample stays
+        printer.keep_drawn();
+        assert!(!printer.render(&stat, &load, Duration::from_secs(1)).contains("\x1b[2F"));
+    }
+
+    #[test]
+    fn sparkline_gaps_are_empty_lines() {
+        let topology = Topology::read_in(sysfs("smt-2x2x2")).unwrap();
+        let mut printer = Printer::new(Format::Glyphs, Vec::new())
+            .history(1)
+            .total(Some(Total::Percent), false)
+            .topology(Some(topology), Some(Grouping::Package), false);
+        let (stat, load) = sample("cpu  0 0 0 200\ncpu0 0 0 0 100\ncpu2 0 0 0 100\n");
+
+        assert_eq!(printer.render(&stat, &load, Duration::from_secs(1)), "  0% ▁\n     ▁\n\n     ▁");
+    }
+}
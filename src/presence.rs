@@ -0,0 +1,40 @@
+use std::{
+    fs,
+    path::Path
+};
+
+use crate::{
+    cpulist::parse_cpulist,
+    error::Error,
+    topology::SYS_ROOT
+};
+
+/// Which CPUs the machine has and which of them are online.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Presence {
+    /// CPUs that are plugged in, whether online or not
+    pub present: Vec<usize>,
+    pub online: Vec<usize>
+}
+
+impl Presence {
+    /// Reads `/sys/devices/system/cpu/present` and `online`.
+    pub fn read() -> Result<Presence, Error> {
+        Presence::read_in(SYS_ROOT)
+    }
+
+    /// Reads `present` and `online` from sysfs mounted at `sys_root`.
+    pub fn read_in<P: AsRef<Path>>(sys_root: P) -> Result<Presence, Error> {
+        let dir = sys_root.as_ref().join("devices/system/cpu");
+        let read = |name| -> Result<Vec<usize>, Error> {
+            Ok(parse_cpulist(&fs::read_to_string(dir.join(name))?)?)
+        };
+
+        Ok(Presence { present: read("present")?, online: read("online")? })
+    }
+
+    /// Whether `idx` is present, but offline.
+    pub fn is_offline(&self, idx: usize) -> bool {
+        self.present.contains(&idx) && !self.online.contains(&idx)
+    }
+}
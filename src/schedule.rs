@@ -0,0 +1,115 @@
+use std::{
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+/// Wakes up every `interval` on a monotonic clock, so the time spent
+/// reading and printing doesn't add up to drift.
+pub struct Ticker {
+    interval: Duration,
+    next: Instant,
+    /// An extra tick before the regular ones
+    early: Option<Instant>
+}
+
+impl Ticker {
+    /// Starts ticking one interval from now, or with `align` at multiples
+    /// of the interval on the wall clock, so that samples from several
+    /// hosts line up.
+    ///
+    /// Aligning sleeps until the next multiple, so that a reading taken
+    /// right after is a whole interval from the first tick.
+    pub fn new(interval: Duration, align: bool) -> Ticker {
+        if align && interval.as_nanos() > 0 {
+            let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            thread::sleep(until_aligned(since_epoch, interval));
+        }
+
+        Ticker { interval, next: Instant::now() + interval, early: None }
+    }
+
+    /// Adds a tick `delay` from now, before the regular ones, so the first
+    /// sample needn't wait a whole interval.
+    pub fn bootstrap(&mut self, delay: Duration) {
+        let early = Instant::now() + delay;
+        if early < self.next {
+            self.early = Some(early);
+        }
+    }
+
+    /// Sleeps until the next tick. Ticks that already passed, e.g. because
+    /// printing blocked, are skipped rather than caught up on.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if let Some(early) = self.early.take() {
+            thread::sleep(early.saturating_duration_since(now));
+            return;
+        }
+
+        if let Some(remaining) = self.next.checked_duration_since(now) {
+            thread::sleep(remaining);
+        }
+        self.next = next_tick(self.next, self.interval, now);
+    }
+}
+
+/// The tick after `next` as of `now`, skipping ticks that already passed.
+fn next_tick(next: Instant, interval: Duration, now: Instant) -> Instant {
+    match now.checked_duration_since(next) {
+        // The first multiple of the interval since `next` that lies ahead
+        Some(behind) if interval.as_nanos() > 0 => now + (interval - into_interval(behind, interval)),
+        _ => next + interval
+    }
+}
+
+/// How long from `since_epoch` until the next multiple of `interval` on
+/// the wall clock.
+fn until_aligned(since_epoch: Duration, interval: Duration) -> Duration {
+    interval - into_interval(since_epoch, interval)
+}
+
+/// How far `time` is past the last multiple of `interval`, which mustn't be zero.
+fn into_interval(time: Duration, interval: Duration) -> Duration {
+    let nanos = time.as_nanos() % interval.as_nanos();
+    Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_to_multiples_of_the_interval() {
+        let interval = Duration::from_secs(5);
+        assert_eq!(until_aligned(Duration::from_millis(1_000_001_300), interval), Duration::from_millis(3700));
+        // Right on a multiple, the next one is a whole interval away
+        assert_eq!(until_aligned(Duration::from_secs(1_000_000_000), interval), interval);
+    }
+
+    #[test]
+    fn ticks_on_schedule() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let next = start + interval;
+        assert_eq!(next_tick(next, interval, start + Duration::from_millis(500)), next + interval);
+        assert_eq!(next_tick(next, interval, next), next + interval);
+    }
+
+    #[test]
+    fn skips_ticks_that_passed() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let next = start + interval;
+        assert_eq!(next_tick(next, interval, next + Duration::from_millis(300)), next + interval);
+        assert_eq!(next_tick(next, interval, next + Duration::from_millis(2300)), next + interval * 3);
+    }
+
+    #[test]
+    fn skips_more_ticks_than_fit_in_u32() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(1);
+        // 100 days of 1ms ticks
+        let stall = Duration::from_secs(100 * 24 * 60 * 60) + Duration::from_micros(400);
+        assert_eq!(next_tick(start, interval, start + stall), start + stall + Duration::from_micros(600));
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use vec_map::VecMap;
+
+use crate::cpu::{Load, CPU};
+
+/// Scale of the counters in smoothed loads, as they only keep the ratios
+/// between states.
+const SCALE: f64 = 1_000_000.;
+
+/// Exponential moving average of the share of time each core spends in
+/// each state, so that short intervals don't flicker.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    half_life: Duration,
+    total: Option<Vec<f64>>,
+    cores: VecMap<Vec<f64>>
+}
+
+impl Ema {
+    /// An average in which a sample weighs half as much after `half_life`.
+    pub fn new(half_life: Duration) -> Ema {
+        Ema { half_life, total: None, cores: VecMap::new() }
+    }
+
+    /// Adds a sample that spans `elapsed` and returns the average so far.
+    ///
+    /// Cores that are missing or invalid in `load` start over when they
+    /// come back.
+    pub fn smooth(&mut self, load: &Load, elapsed: Duration) -> Load {
+        let weight = if self.half_life.as_nanos() == 0 {
+            1.
+        } else {
+            1. - 0.5f64.powf(elapsed.as_secs_f64() / self.half_life.as_secs_f64())
+        };
+
+        let total = match &load.total {
+            Some(total) => Some(update(self.total.get_or_insert_with(Vec::new), total, weight)),
+            None => { self.total = None; None }
+        };
+
+        self.cores.retain(|idx, _| load.cores.contains_key(idx));
+        let cores = load.cores.iter()
+            .map(|(idx, core)| (idx, update(self.cores.entry(idx).or_insert_with(Vec::new), core, weight)))
+            .collect();
+
+        Load { total, cores, invalid: load.invalid.clone() }
+    }
+}
+
+/// Moves `average` towards the shares of `core` by `weight`, starting
+/// from them if there's no average yet.
+fn update(average: &mut Vec<f64>, core: &CPU, weight: f64) -> CPU {
+    let fields = core.fields();
+    let total = core.total_time() as f64;
+
+    // A sample without time passing tells nothing
+    if total > 0. {
+        let shares = fields.iter().map(|&field| field as f64 / total);
+        if average.len() != fields.len() {
+            *average = shares.collect();
+        } else {
+            for (avg, share) in average.iter_mut().zip(shares) {
+                *avg += weight * (share - *avg);
+            }
+        }
+    }
+
+    let scaled: Vec<u64> = average.iter().map(|avg| (avg * SCALE).round() as u64).collect();
+    CPU::from_fields(&scaled)
+}
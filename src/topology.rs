@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    path::Path
+};
+
+use vec_map::VecMap;
+
+use crate::{
+    cpu::Load,
+    cpulist::parse_cpulist,
+    error::{Error, ParseError}
+};
+
+/// Where sysfs is usually mounted
+pub const SYS_ROOT: &str = "/sys";
+
+/// The kind of core on hybrid CPUs like Intel's Alder Lake or ARM's
+/// big.LITTLE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CoreKind {
+    Performance,
+    Efficiency
+}
+
+/// Where a logical CPU sits in the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreTopology {
+    /// The socket, from `physical_package_id`
+    pub package: usize,
+    /// The NUMA node, if the kernel has NUMA support
+    pub node: Option<usize>,
+    /// The physical core within its package, from `core_id`
+    pub core: usize,
+    /// All logical CPUs of the same physical core, including this one
+    pub siblings: Vec<usize>,
+    /// `None` unless the CPU is hybrid
+    pub kind: Option<CoreKind>
+}
+
+/// The topology of all online CPUs.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    cpus: VecMap<CoreTopology>
+}
+
+impl Topology {
+    /// Reads `/sys/devices/system/cpu` and `/sys/devices/system/node`, and
+    /// the core types of hybrid CPUs.
+    pub fn read() -> Result<Topology, Error> {
+        Topology::read_in(SYS_ROOT)
+    }
+
+    /// Reads the topology from sysfs mounted at `sys_root`.
+    ///
+    /// CPUs without topology, usually because they are offline, are left out.
+    pub fn read_in<P: AsRef<Path>>(sys_root: P) -> Result<Topology, Error> {
+        let devices = sys_root.as_ref().join("devices");
+        let system = devices.join("system");
+        let mut topology = Topology::default();
+        let mut capacities = VecMap::new();
+
+        for entry in fs::read_dir(system.join("cpu"))? {
+            let entry = entry?;
+            let idx = match numbered(&entry.file_name().to_string_lossy(), "cpu") {
+                Some(idx) => idx,
+                None => continue
+            };
+
+            let dir = entry.path().join("topology");
+            let read = |name: &str| fs::read_to_string(dir.join(name));
+            let (package, core) = match (read("physical_package_id"), read("core_id")) {
+                (Ok(package), Ok(core)) => (id(&package)?, id(&core)?),
+                _ => continue
+            };
+            // Renamed in Linux 5.7, the old name is deprecated
+            let siblings = match read("core_cpus_list").or_else(|_| read("thread_siblings_list")) {
+                Ok(list) => parse_cpulist(&list)?,
+                Err(_) => vec![idx]
+            };
+
+            // Relative performance on asymmetric ARM systems
+            if let Ok(capacity) = fs::read_to_string(entry.path().join("cpu_capacity")) {
+                capacities.insert(idx, id(&capacity)?);
+            }
+
+            topology.cpus.insert(idx, CoreTopology { package, node: None, core, siblings, kind: None });
+        }
+
+        // Intel hybrid CPUs have a PMU per core type, listing its CPUs
+        let pmus = [("cpu_core", CoreKind::Performance), ("cpu_atom", CoreKind::Efficiency)];
+        let mut hybrid = false;
+        for (pmu, kind) in &pmus {
+            if let Ok(list) = fs::read_to_string(devices.join(pmu).join("cpus")) {
+                hybrid = true;
+                for idx in parse_cpulist(&list)? {
+                    if let Some(cpu) = topology.cpus.get_mut(idx) {
+                        cpu.kind = Some(*kind);
+                    }
+                }
+            }
+        }
+
+        // Otherwise the cores with the lowest capacity are efficiency cores,
+        // and all others, like big and prime cores, are performance cores
+        let min_capacity = capacities.values().copied().min();
+        if !hybrid && capacities.values().any(|&capacity| Some(capacity) != min_capacity) {
+            for (idx, &capacity) in capacities.iter() {
+                if let Some(cpu) = topology.cpus.get_mut(idx) {
+                    cpu.kind = Some(if Some(capacity) == min_capacity { CoreKind::Efficiency } else { CoreKind::Performance });
+                }
+            }
+        }
+
+        // Without NUMA support there are no nodes at all
+        if let Ok(nodes) = fs::read_dir(system.join("node")) {
+            for entry in nodes {
+                let entry = entry?;
+                let node = match numbered(&entry.file_name().to_string_lossy(), "node") {
+                    Some(node) => node,
+                    None => continue
+                };
+
+                for idx in parse_cpulist(&fs::read_to_string(entry.path().join("cpulist"))?)? {
+                    if let Some(cpu) = topology.cpus.get_mut(idx) {
+                        cpu.node = Some(node);
+                    }
+                }
+            }
+        }
+
+        Ok(topology)
+    }
+
+    pub fn cpu(&self, idx: usize) -> Option<&CoreTopology> {
+        self.cpus.get(idx)
+    }
+
+    /// The lowest-numbered sibling of `idx`, which stands in for its whole
+    /// physical core.
+    pub fn first_sibling(&self, idx: usize) -> usize {
+        self.cpus.get(idx)
+            .and_then(|cpu| cpu.siblings.iter().copied().min())
+            .unwrap_or(idx)
+    }
+
+    /// The load per physical core, adding up the time of all SMT siblings
+    /// under the [`first_sibling`](Topology::first_sibling).
+    ///
+    /// A physical core is invalid if any of its siblings is.
+    pub fn merge_siblings(&self, load: &Load) -> Load {
+        let mut merged = Load { total: load.total.clone(), ..Load::default() };
+
+        for (idx, core) in load.cores.iter() {
+            let first = self.first_sibling(idx);
+            let sum = match merged.cores.get(first) {
+                Some(sum) => sum.add(core),
+                None => core.clone()
+            };
+            merged.cores.insert(first, sum);
+        }
+
+        for &idx in &load.invalid {
+            let first = self.first_sibling(idx);
+            merged.cores.remove(first);
+            if !merged.invalid.contains(&first) {
+                merged.invalid.push(first);
+            }
+        }
+
+        merged
+    }
+}
+
+/// The number in names like `cpu12`, `None` for other names like `cpufreq`.
+fn numbered(name: &str, prefix: &str) -> Option<usize> {
+    name.strip_prefix(prefix)?.parse().ok()
+}
+
+/// An id from sysfs. Some platforms report -1 for unknown packages, which
+/// is as good as a single one.
+fn id(s: &str) -> Result<usize, ParseError> {
+    let s = s.trim();
+    match s.parse::<i64>() {
+        Ok(id) => Ok(id.max(0) as usize),
+        Err(_) => Err(ParseError::BadNumber { field: "id", line: s.to_owned() })
+    }
+}
@@ -0,0 +1,26 @@
+use std::{
+    fs,
+    path::Path,
+    time::Duration
+};
+
+use crate::error::{Error, ParseError};
+
+/// Reads the time since boot from `uptime` in procfs mounted at `proc_root`.
+///
+/// Unlike [`Instant`](std::time::Instant), which doesn't advance while the
+/// system is suspended, this is `CLOCK_BOOTTIME` and includes suspend.
+pub fn read_uptime<P: AsRef<Path>>(proc_root: P) -> Result<Duration, Error> {
+    let line = fs::read_to_string(proc_root.as_ref().join("uptime"))?;
+    let line = line.trim_end();
+
+    let seconds = line.split_whitespace().next()
+        .ok_or_else(|| ParseError::MissingField { field: "uptime", line: line.to_owned() })?;
+    let seconds: f64 = seconds.parse()
+        .map_err(|_| ParseError::BadNumber { field: "uptime", line: line.to_owned() })?;
+    if !seconds.is_finite() || seconds < 0. {
+        return Err(ParseError::BadNumber { field: "uptime", line: line.to_owned() }.into());
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
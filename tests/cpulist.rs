@@ -0,0 +1,18 @@
+use cpuline::{parse_cpulist, ParseError};
+
+#[test]
+fn ranges_and_singles_keep_their_order() {
+    assert_eq!(parse_cpulist("8,0-3,10-11\n").unwrap(), [8, 0, 1, 2, 3, 10, 11]);
+}
+
+#[test]
+fn empty_list() {
+    assert_eq!(parse_cpulist("\n").unwrap(), Vec::<usize>::new());
+}
+
+#[test]
+fn malformed() {
+    for list in &["0-", "a", "3-1", "1,,x", "0-9999999999", "65536"] {
+        assert_eq!(parse_cpulist(list), Err(ParseError::BadCpuList { line: list.to_string() }));
+    }
+}
@@ -0,0 +1,132 @@
+//! Golden-file tests for the `/proc/stat` parser and load math.
+//!
+//! Every directory in `tests/fixtures/captured` and `tests/fixtures/synthetic`
+//! holds two readings, `before` and `after`, and the `expected` rendering of
+//! the load between them. Captured readings come from real systems, synthetic
+//! ones stand in for systems that weren't at hand, see
+//! `tests/fixtures/README.md`. Run with `UPDATE_GOLDEN=1` to rewrite
+//! `expected` after an intended change.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf}
+};
+
+use cpuline::{Load, ParseError, Stat, CPU};
+
+fn fixtures(suite: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(suite)
+}
+
+fn read(dir: &Path, name: &str) -> Stat {
+    let file = fs::File::open(dir.join(name)).unwrap();
+    Stat::read_from(std::io::BufReader::new(file))
+        .unwrap_or_else(|e| panic!("{}/{}: {}", dir.display(), name, e))
+}
+
+fn describe(out: &mut String, label: &str, cpu: &CPU) {
+    let busy = 100. * cpu.busy_time() as f64 / cpu.total_time() as f64;
+    writeln!(out, "{} user={} system={} iowait={} steal={} idle={} extra={:?} busy={} total={} busy%={:.1}",
+             label, cpu.user_time(), cpu.system_time(), cpu.iowait(), cpu.steal(), cpu.idle(),
+             cpu.extra(), cpu.busy_time(), cpu.total_time(), busy).unwrap();
+}
+
+fn render(load: &Load) -> String {
+    let mut out = String::new();
+    match &load.total {
+        Some(total) => describe(&mut out, "total", total),
+        None => out.push_str("total none\n")
+    }
+    for (idx, core) in load.cores.iter() {
+        describe(&mut out, &format!("cpu{}", idx), core);
+    }
+    for idx in &load.invalid {
+        writeln!(out, "invalid cpu{}", idx).unwrap();
+    }
+    out
+}
+
+fn check(suite: &str) {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut dirs: Vec<_> = fs::read_dir(fixtures(suite)).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    assert!(!dirs.is_empty());
+
+    let mut failed = Vec::new();
+    for dir in &dirs {
+        let load = read(dir, "after").load_since(&read(dir, "before"));
+        let actual = render(&load);
+        let expected_path = dir.join("expected");
+
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+        } else if fs::read_to_string(&expected_path).ok().as_deref() != Some(&actual) {
+            eprintln!("{}: got\n{}", dir.display(), actual);
+            failed.push(dir.file_name().unwrap().to_owned());
+        }
+    }
+
+    assert!(failed.is_empty(), "mismatched fixtures: {:?}", failed);
+}
+
+#[test]
+fn captured() {
+    check("captured");
+}
+
+#[test]
+fn synthetic() {
+    check("synthetic");
+}
+
+#[test]
+fn offline_cores_keep_their_numbers() {
+    let stat = read(&fixtures("synthetic").join("offline-cores"), "before");
+    let cores: Vec<_> = stat.cores().keys().collect();
+    assert_eq!(cores, [0, 1, 3, 4, 6, 7]);
+}
+
+#[test]
+fn missing_required_field() {
+    let err = Stat::read_from("cpu  1 2 3\n".as_bytes()).unwrap_err();
+    match err {
+        cpuline::Error::Parse(ParseError::MissingField { field, line }) => {
+            assert_eq!(field, "idle");
+            assert_eq!(line, "cpu  1 2 3");
+        },
+        e => panic!("unexpected error: {}", e)
+    }
+}
+
+#[test]
+fn bad_number() {
+    let err = CPU::from_line(" 1 2 x 4").unwrap_err();
+    assert_eq!(err, ParseError::BadNumber { field: "system", line: " 1 2 x 4".to_owned() });
+}
+
+#[test]
+fn bad_cpu_index() {
+    for input in &["cpux 1 2 3 4\n", "cpu1\n"] {
+        match Stat::read_from(input.as_bytes()) {
+            Err(cpuline::Error::Parse(ParseError::BadCpuIndex { .. })) => (),
+            other => panic!("{:?}: unexpected {:?}", input, other)
+        }
+    }
+}
+
+#[test]
+fn since_boot_is_the_raw_counters() {
+    let stat = read(&fixtures("captured").join("vm-steal"), "after");
+    let load = stat.since_boot();
+    assert_eq!(load.total.as_ref().unwrap().total_time(), stat.total().unwrap().total_time());
+    for (idx, core) in stat.cores() {
+        assert_eq!(load.cores[idx].steal(), core.steal());
+        assert_eq!(load.cores[idx].busy_time(), core.busy_time());
+    }
+    assert!(load.invalid.is_empty());
+}
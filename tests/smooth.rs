@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use cpuline::{Ema, Stat};
+
+fn load(before: &str, after: &str) -> cpuline::Load {
+    let before = Stat::read_from(before.as_bytes()).unwrap();
+    let after = Stat::read_from(after.as_bytes()).unwrap();
+    after.load_since(&before)
+}
+
+fn busy(load: &cpuline::Load, idx: usize) -> f64 {
+    let core = load.cores.get(idx).unwrap();
+    core.busy_time() as f64 / core.total_time() as f64
+}
+
+#[test]
+fn first_sample_is_unchanged() {
+    let mut ema = Ema::new(Duration::from_secs(1));
+    let smoothed = ema.smooth(&load("cpu0 0 0 0 0\n", "cpu0 30 0 10 60\n"), Duration::from_millis(100));
+    assert!((busy(&smoothed, 0) - 0.4).abs() < 1e-6);
+}
+
+#[test]
+fn moves_halfway_after_half_life() {
+    let mut ema = Ema::new(Duration::from_secs(1));
+    ema.smooth(&load("cpu0 0 0 0 0\n", "cpu0 0 0 0 100\n"), Duration::from_secs(1));
+    let smoothed = ema.smooth(&load("cpu0 0 0 0 100\n", "cpu0 100 0 0 100\n"), Duration::from_secs(1));
+    assert!((busy(&smoothed, 0) - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn vanished_cores_start_over() {
+    let mut ema = Ema::new(Duration::from_secs(1));
+    ema.smooth(&load("cpu0 0 0 0 0\n", "cpu0 0 0 0 100\n"), Duration::from_secs(1));
+    ema.smooth(&load("cpu1 0 0 0 0\n", "cpu1 0 0 0 100\n"), Duration::from_secs(1));
+    let smoothed = ema.smooth(&load("cpu0 0 0 0 100\n", "cpu0 100 0 0 100\n"), Duration::from_secs(1));
+    assert!((busy(&smoothed, 0) - 1.).abs() < 1e-6);
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use cpuline::{CoreKind, Presence, Stat, Topology};
+
+fn sysfs(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "sysfs", name].iter().collect()
+}
+
+#[test]
+fn reads_packages_nodes_and_siblings() {
+    let topology = Topology::read_in(sysfs("smt-2x2x2")).unwrap();
+
+    let cpu6 = topology.cpu(6).unwrap();
+    assert_eq!((cpu6.package, cpu6.node, cpu6.core), (1, Some(1), 0));
+    assert_eq!(cpu6.siblings, [2, 6]);
+    assert_eq!(topology.first_sibling(6), 2);
+    assert!(topology.cpu(8).is_none());
+}
+
+#[test]
+fn merges_siblings_under_the_first() {
+    let topology = Topology::read_in(sysfs("smt-2x2x2")).unwrap();
+    let before = Stat::read_from("cpu1 0 0 0 0\ncpu5 0 0 0 0\ncpu2 10 0 0 0\ncpu6 0 0 0 0\n".as_bytes()).unwrap();
+    let after = Stat::read_from("cpu1 10 0 0 10\ncpu5 0 0 20 0\ncpu2 0 0 0 0\ncpu6 0 0 0 5\n".as_bytes()).unwrap();
+
+    let merged = topology.merge_siblings(&after.load_since(&before));
+    let keys: Vec<_> = merged.cores.keys().collect();
+    assert_eq!(keys, [1]);
+    assert_eq!(merged.cores[1].busy_time(), 30);
+    assert_eq!(merged.cores[1].total_time(), 40);
+    assert_eq!(merged.invalid, [2]);
+}
+
+#[test]
+fn hybrid_core_kinds_from_pmus() {
+    let topology = Topology::read_in(sysfs("intel-hybrid")).unwrap();
+    assert_eq!(topology.cpu(1).unwrap().kind, Some(CoreKind::Performance));
+    assert_eq!(topology.cpu(1).unwrap().siblings, [0, 1]);
+    assert_eq!(topology.cpu(5).unwrap().kind, Some(CoreKind::Efficiency));
+}
+
+#[test]
+fn hybrid_core_kinds_from_capacity() {
+    // Little, big and prime cores
+    let topology = Topology::read_in(sysfs("arm-big-little")).unwrap();
+    assert_eq!(topology.cpu(0).unwrap().kind, Some(CoreKind::Efficiency));
+    assert_eq!(topology.cpu(3).unwrap().kind, Some(CoreKind::Efficiency));
+    assert_eq!(topology.cpu(4).unwrap().kind, Some(CoreKind::Performance));
+    assert_eq!(topology.cpu(7).unwrap().kind, Some(CoreKind::Performance));
+}
+
+#[test]
+fn symmetric_cores_have_no_kind() {
+    let topology = Topology::read_in(sysfs("smt-2x2x2")).unwrap();
+    assert_eq!(topology.cpu(0).unwrap().kind, None);
+}
+
+#[test]
+fn offline_cores_are_present() {
+    let presence = Presence::read_in(sysfs("offline")).unwrap();
+    assert_eq!(presence.present, [0, 1, 2, 3]);
+    assert!(presence.is_offline(2));
+    assert!(!presence.is_offline(3));
+    assert!(!presence.is_offline(4));
+}